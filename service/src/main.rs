@@ -1,8 +1,9 @@
 use std::error::Error;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
-use asndb::store::IPDatabase;
+use asndb::reload::{DbHandle, Source};
 use bytes::Bytes;
 use http_body_util::Full;
 use hyper::header::HeaderValue;
@@ -12,7 +13,6 @@ use hyper::{Response, StatusCode};
 use hyper_util::rt::TokioIo;
 use log::info;
 use tokio::net::TcpListener;
-use tokio::sync::RwLock;
 
 // type GenericError = Box<dyn std::error::Error + Send + Sync>;
 // type BoxBody = http_body_util::combinators::BoxBody<Bytes, hyper::Error>;
@@ -20,6 +20,8 @@ use tokio::sync::RwLock;
 // static INTERNAL_SERVER_ERROR: &[u8] = b"Internal Server Error";
 // static NOTFOUND: &[u8] = b"Not Found";
 
+const RELOAD_INTERVAL: Duration = Duration::from_secs(300);
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     femme::start();
@@ -29,9 +31,11 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let listener = TcpListener::bind(&addr).await?;
     info!("Listening on http://{}", addr);
 
-    let db = Arc::new(RwLock::new(IPDatabase::load_from_file(
-        "./ip_database.bin",
-    )?));
+    let db = Arc::new(DbHandle::load(Source::Binary(
+        "./ip_database.bin".to_string(),
+    ))?);
+    db.spawn_refresh(RELOAD_INTERVAL);
+
     loop {
         let (stream, _) = listener.accept().await?;
         let io = TokioIo::new(stream);
@@ -43,28 +47,39 @@ async fn main() -> Result<(), Box<dyn Error>> {
                     service_fn(move |_req| {
                         let db = db.clone();
                         async move {
-                            if _req.uri().path() != "/api/ip" {
-                                return Ok(Response::builder()
-                                    .status(StatusCode::NOT_FOUND)
-                                    .body(Full::new(Bytes::new()))
-                                    .unwrap());
-                            }
-                            if let Some(a) = db.read().await.query(_req.uri().query().unwrap()) {
-                                let mut r = Response::new(Full::new(Bytes::from(
-                                    serde_json::to_vec(&a)
-                                        //todo
-                                        .unwrap(),
-                                )));
-                                r.headers_mut().append(
-                                    "Content-Type",
-                                    HeaderValue::from_str("application/json").unwrap(),
-                                );
-                                Ok::<_, hyper::Error>(r)
-                            } else {
-                                Ok(Response::builder()
-                                    .status(StatusCode::NOT_FOUND)
-                                    .body(Full::new(Bytes::new()))
-                                    .unwrap())
+                            match _req.uri().path() {
+                                "/api/ip" => {
+                                    let current = db.current();
+                                    if let Some(a) =
+                                        _req.uri().query().and_then(|ip| current.query(ip))
+                                    {
+                                        Ok::<_, hyper::Error>(json_response(
+                                            serde_json::to_vec(&a).unwrap(),
+                                        ))
+                                    } else {
+                                        Ok(not_found())
+                                    }
+                                }
+                                "/api/status" => {
+                                    let status = db.status();
+                                    let body = serde_json::json!({
+                                        "loaded_at": status
+                                            .loaded_at
+                                            .duration_since(std::time::UNIX_EPOCH)
+                                            .unwrap_or_default()
+                                            .as_secs(),
+                                        "entry_count": status.entry_count,
+                                    });
+                                    Ok(json_response(serde_json::to_vec(&body).unwrap()))
+                                }
+                                "/api/reload" => {
+                                    let before = Arc::clone(&db.current());
+                                    db.reload();
+                                    let reloaded = !Arc::ptr_eq(&before, &db.current());
+                                    let body = serde_json::json!({ "reloaded": reloaded });
+                                    Ok(json_response(serde_json::to_vec(&body).unwrap()))
+                                }
+                                _ => Ok(not_found()),
                             }
                         }
                     }),
@@ -76,3 +91,19 @@ async fn main() -> Result<(), Box<dyn Error>> {
         });
     }
 }
+
+fn json_response(body: Vec<u8>) -> Response<Full<Bytes>> {
+    let mut r = Response::new(Full::new(Bytes::from(body)));
+    r.headers_mut().append(
+        "Content-Type",
+        HeaderValue::from_str("application/json").unwrap(),
+    );
+    r
+}
+
+fn not_found() -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Full::new(Bytes::new()))
+        .unwrap()
+}
@@ -0,0 +1,238 @@
+//! Minimal-CIDR decomposition of `[start, end]` ranges, and plain-text
+//! export of the result for firewall tooling (nftables sets, ipset).
+
+use crate::store::IPDatabase;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// A single CIDR block: network address + prefix length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    pub address: IpAddr,
+    pub prefix_len: u8,
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix_len)
+    }
+}
+
+/// The handful of integer operations the greedy CIDR algorithm needs,
+/// implemented for the two address widths ranges are stored as.
+trait CidrAddr: Copy + Eq + Ord {
+    const WIDTH: u32;
+    const ZERO: Self;
+    const ONE: Self;
+    const MAX: Self;
+    fn trailing_zeros(self) -> u32;
+    fn leading_zeros(self) -> u32;
+    fn shl(self, n: u32) -> Self;
+    fn wrapping_add(self, rhs: Self) -> Self;
+    fn wrapping_sub(self, rhs: Self) -> Self;
+}
+
+impl CidrAddr for u32 {
+    const WIDTH: u32 = 32;
+    const ZERO: Self = 0;
+    const ONE: Self = 1;
+    const MAX: Self = u32::MAX;
+    fn trailing_zeros(self) -> u32 {
+        u32::trailing_zeros(self)
+    }
+    fn leading_zeros(self) -> u32 {
+        u32::leading_zeros(self)
+    }
+    fn shl(self, n: u32) -> Self {
+        self << n
+    }
+    fn wrapping_add(self, rhs: Self) -> Self {
+        u32::wrapping_add(self, rhs)
+    }
+    fn wrapping_sub(self, rhs: Self) -> Self {
+        u32::wrapping_sub(self, rhs)
+    }
+}
+
+impl CidrAddr for u128 {
+    const WIDTH: u32 = 128;
+    const ZERO: Self = 0;
+    const ONE: Self = 1;
+    const MAX: Self = u128::MAX;
+    fn trailing_zeros(self) -> u32 {
+        u128::trailing_zeros(self)
+    }
+    fn leading_zeros(self) -> u32 {
+        u128::leading_zeros(self)
+    }
+    fn shl(self, n: u32) -> Self {
+        self << n
+    }
+    fn wrapping_add(self, rhs: Self) -> Self {
+        u128::wrapping_add(self, rhs)
+    }
+    fn wrapping_sub(self, rhs: Self) -> Self {
+        u128::wrapping_sub(self, rhs)
+    }
+}
+
+/// Classic greedy range-to-CIDR decomposition, generic over the address
+/// width. Returns `(block_start, prefix_len)` pairs covering `[start, end]`.
+fn decompose<T: CidrAddr>(start: T, end: T) -> Vec<(T, u8)> {
+    // The full address space (e.g. 0.0.0.0/0) is the one range whose record
+    // count doesn't fit back into T, so it's handled up front.
+    if start == T::ZERO && end == T::MAX {
+        return vec![(start, 0)];
+    }
+
+    let mut blocks = Vec::new();
+    let mut cur = start;
+    let mut remaining = end.wrapping_sub(cur).wrapping_add(T::ONE);
+
+    loop {
+        // `start == 0` has no alignment constraint of its own; everything
+        // else is capped by how many low zero-bits it has.
+        let align_bits = if cur == T::ZERO {
+            T::WIDTH
+        } else {
+            cur.trailing_zeros()
+        };
+        let size_bits = align_bits.min(T::WIDTH - 1 - remaining.leading_zeros());
+        let size = T::ONE.shl(size_bits);
+
+        blocks.push((cur, (T::WIDTH - size_bits) as u8));
+
+        remaining = remaining.wrapping_sub(size);
+        if remaining == T::ZERO {
+            break;
+        }
+        cur = cur.wrapping_add(size);
+    }
+
+    blocks
+}
+
+fn decompose_range(start: IpAddr, end: IpAddr) -> Vec<Cidr> {
+    match (start, end) {
+        (IpAddr::V4(start), IpAddr::V4(end)) => decompose(u32::from(start), u32::from(end))
+            .into_iter()
+            .map(|(addr, prefix_len)| Cidr {
+                address: IpAddr::V4(Ipv4Addr::from(addr)),
+                prefix_len,
+            })
+            .collect(),
+        (IpAddr::V6(start), IpAddr::V6(end)) => decompose(u128::from(start), u128::from(end))
+            .into_iter()
+            .map(|(addr, prefix_len)| Cidr {
+                address: IpAddr::V6(Ipv6Addr::from(addr)),
+                prefix_len,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+impl IPDatabase {
+    /// Minimal CIDR cover of every range announced by `asn`.
+    pub fn cidrs_for_asn(&self, asn: u32) -> Vec<Cidr> {
+        self.ranges_for_asn(asn)
+            .flat_map(|(start, end)| decompose_range(start, end))
+            .collect()
+    }
+
+    /// Minimal CIDR cover of every range registered under `country`.
+    pub fn cidrs_for_country(&self, country: &str) -> Vec<Cidr> {
+        self.asns_for_country(country)
+            .flat_map(|asn| self.cidrs_for_asn(asn))
+            .collect()
+    }
+}
+
+/// Renders `cidrs` as an nftables set body: `elements = { ... }`.
+pub fn to_nftables_elements(cidrs: &[Cidr]) -> String {
+    let elements = cidrs
+        .iter()
+        .map(Cidr::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("elements = {{ {elements} }}")
+}
+
+/// Renders `cidrs` as `ipset add <set_name> <cidr>` lines.
+pub fn to_ipset_adds(cidrs: &[Cidr], set_name: &str) -> String {
+    cidrs
+        .iter()
+        .map(|cidr| format!("add {set_name} {cidr}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_address_is_a_host_route() {
+        assert_eq!(decompose(10u32, 10u32), vec![(10u32, 32)]);
+    }
+
+    #[test]
+    fn aligned_power_of_two_block_stays_whole() {
+        // 10.0.0.0 - 10.0.0.3 is exactly 10.0.0.0/30.
+        assert_eq!(decompose(10u32 << 24, (10u32 << 24) + 3), vec![(10u32 << 24, 30)]);
+    }
+
+    #[test]
+    fn unaligned_range_splits_into_minimal_blocks() {
+        // 10.0.0.1 - 10.0.0.2 can't be one block: .1/31 would include .0.
+        assert_eq!(
+            decompose((10u32 << 24) + 1, (10u32 << 24) + 2),
+            vec![((10u32 << 24) + 1, 32), ((10u32 << 24) + 2, 32)]
+        );
+    }
+
+    #[test]
+    fn all_zeros_start_has_no_alignment_cap() {
+        // 0.0.0.0 - 0.0.0.1 should collapse to 0.0.0.0/31, not two /32s: the
+        // special-cased alignment for `start == 0` must not under-cap it.
+        assert_eq!(decompose(0u32, 1u32), vec![(0u32, 31)]);
+    }
+
+    #[test]
+    fn full_v4_space_is_the_default_route() {
+        assert_eq!(decompose(0u32, u32::MAX), vec![(0u32, 0)]);
+    }
+
+    #[test]
+    fn full_v6_space_is_the_default_route_without_overflow() {
+        assert_eq!(decompose(0u128, u128::MAX), vec![(0u128, 0)]);
+    }
+
+    #[test]
+    fn u128_range_near_the_top_of_the_address_space_does_not_overflow() {
+        let start = u128::MAX - 3;
+        assert_eq!(decompose(start, u128::MAX), vec![(start, 126)]);
+    }
+
+    #[test]
+    fn decompose_range_renders_v4_cidrs() {
+        let cidrs = decompose_range(
+            IpAddr::V4(Ipv4Addr::new(192, 168, 0, 0)),
+            IpAddr::V4(Ipv4Addr::new(192, 168, 0, 3)),
+        );
+        assert_eq!(cidrs, vec![Cidr {
+            address: IpAddr::V4(Ipv4Addr::new(192, 168, 0, 0)),
+            prefix_len: 30,
+        }]);
+    }
+
+    #[test]
+    fn exporters_render_expected_text() {
+        let cidrs = vec![Cidr {
+            address: IpAddr::V4(Ipv4Addr::new(192, 168, 0, 0)),
+            prefix_len: 30,
+        }];
+        assert_eq!(to_nftables_elements(&cidrs), "elements = { 192.168.0.0/30 }");
+        assert_eq!(to_ipset_adds(&cidrs, "blocklist"), "add blocklist 192.168.0.0/30");
+    }
+}
@@ -0,0 +1,7 @@
+pub mod cidr;
+pub mod reload;
+pub mod store;
+pub mod stringpool;
+
+#[cfg(feature = "mmap")]
+pub mod mmap;
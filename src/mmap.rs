@@ -0,0 +1,358 @@
+//! Zero-copy querier for the `.bin` format, backed by `memmap2`.
+//!
+//! Unlike [`IPDatabase::load_from_file`](crate::store::IPDatabase::load_from_file), which
+//! copies the whole file (including the, by far largest, range tables) into
+//! owned `BTreeSet`s, [`MmapIPDatabase::open`] only materializes the small ASN
+//! map and string pool in RAM. The IPv4/IPv6 range tables stay memory-mapped
+//! and are binary-searched directly against the file's backing pages, bounded
+//! by the 16-bit prefix jump table: read in place when the file carries one,
+//! rebuilt and kept in RAM for older files that don't.
+
+use crate::store::{
+    ASNEntry, IPDatabase, ASN_RECORD_SIZE, HEADER_SIZE, INDEX_BUCKETS, INDEX_SECTION_SIZE,
+    IPV4_RECORD_SIZE, IPV6_RECORD_SIZE, OLD_VERSION, SIGNATURE, VERSION,
+};
+use crate::stringpool::StringPool;
+use byteorder::{BigEndian, ByteOrder};
+use memmap2::{Mmap, MmapOptions};
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::BuildHasherDefault;
+use std::io;
+use std::net::IpAddr;
+use twox_hash::XxHash64;
+
+/// A 16-bit prefix jump table, either borrowed straight out of the mmap (when
+/// the file carries one) or rebuilt in RAM for older files that don't.
+enum Index {
+    OnDisk(usize),
+    Owned(Vec<u32>),
+}
+
+impl Index {
+    #[inline]
+    fn get(&self, mmap: &Mmap, bucket: usize) -> u32 {
+        match self {
+            Index::OnDisk(offset) => {
+                let base = offset + bucket * 4;
+                BigEndian::read_u32(&mmap[base..base + 4])
+            }
+            Index::Owned(table) => table[bucket],
+        }
+    }
+}
+
+/// Memory-mapped alternative to [`IPDatabase`](crate::store::IPDatabase) that keeps only the
+/// ASN map (plus, for older files, the rebuilt prefix jump tables) in RAM and
+/// queries the range tables in place.
+pub struct MmapIPDatabase {
+    mmap: Mmap,
+    asn_map: HashMap<u32, ASNEntry, BuildHasherDefault<XxHash64>>,
+    ipv4_offset: usize,
+    ipv4_count: usize,
+    ipv4_index: Index,
+    ipv6_offset: usize,
+    ipv6_count: usize,
+    ipv6_index: Index,
+}
+
+impl MmapIPDatabase {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let mmap = unsafe { MmapOptions::new().map(&file)? };
+
+        if mmap.len() < HEADER_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Truncated header",
+            ));
+        }
+        let header = &mmap[..HEADER_SIZE];
+        if &header[..16] != SIGNATURE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid signature",
+            ));
+        }
+        let version = BigEndian::read_u16(&header[16..18]);
+        if version != VERSION && version != OLD_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid version",
+            ));
+        }
+
+        let strpl_position = BigEndian::read_u32(&header[18..22]) as usize;
+        let str_length = BigEndian::read_u32(&header[22..26]) as usize;
+        let asn_count = BigEndian::read_u32(&header[30..34]) as usize;
+        let ipv4_count = BigEndian::read_u32(&header[34..38]) as usize;
+        let ipv6_count = BigEndian::read_u32(&header[38..42]) as usize;
+
+        let strpl_bytes = &mmap[strpl_position..strpl_position + str_length];
+        let strpool = StringPool::load(
+            std::str::from_utf8(strpl_bytes)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                .to_string(),
+        );
+
+        let asn_offset = if version == VERSION {
+            HEADER_SIZE + 2 * INDEX_SECTION_SIZE
+        } else {
+            HEADER_SIZE
+        };
+
+        let mut asn_map = HashMap::<_, _, BuildHasherDefault<XxHash64>>::default();
+        for i in 0..asn_count {
+            let base = asn_offset + i * ASN_RECORD_SIZE;
+            let asn = BigEndian::read_u32(&mmap[base..base + 4]);
+            let mut country = [0u8; 2];
+            country.copy_from_slice(&mmap[base + 4..base + 6]);
+            let mut des = [0u8; 8];
+            des.copy_from_slice(&mmap[base + 6..base + 14]);
+            let description = strpool.unpack(&des).to_string();
+            asn_map.insert(
+                asn,
+                ASNEntry {
+                    asn,
+                    country,
+                    description,
+                },
+            );
+        }
+
+        let ipv4_offset = asn_offset + asn_count * ASN_RECORD_SIZE;
+        let ipv6_offset = ipv4_offset + ipv4_count * IPV4_RECORD_SIZE;
+
+        let (ipv4_index, ipv6_index) = if version == VERSION {
+            (
+                Index::OnDisk(HEADER_SIZE),
+                Index::OnDisk(HEADER_SIZE + INDEX_SECTION_SIZE),
+            )
+        } else {
+            // Older files never carried a jump table: rebuild both in RAM by
+            // scanning the (already mapped) record arrays once.
+            let ipv4_table =
+                Self::rebuild_index(&mmap, ipv4_offset, ipv4_count, IPV4_RECORD_SIZE, |b| {
+                    (b as u32) << 16
+                });
+            let ipv6_table =
+                Self::rebuild_index(&mmap, ipv6_offset, ipv6_count, IPV6_RECORD_SIZE, |b| {
+                    (b as u128) << 112
+                });
+            (Index::Owned(ipv4_table), Index::Owned(ipv6_table))
+        };
+
+        Ok(Self {
+            mmap,
+            asn_map,
+            ipv4_offset,
+            ipv4_count,
+            ipv4_index,
+            ipv6_offset,
+            ipv6_count,
+            ipv6_index,
+        })
+    }
+
+    fn rebuild_index<T: Ord + Copy + TryFromBigEndian>(
+        mmap: &Mmap,
+        offset: usize,
+        count: usize,
+        stride: usize,
+        bucket_start: impl Fn(usize) -> T,
+    ) -> Vec<u32> {
+        let mut table = vec![0u32; INDEX_BUCKETS];
+        let mut i = 0usize;
+        let mut seen = 0u32;
+        for (b, slot) in table.iter_mut().enumerate() {
+            let boundary = bucket_start(b);
+            while i < count {
+                let base = offset + i * stride;
+                let start = T::read_be(&mmap[base..base + std::mem::size_of::<T>()]);
+                if start < boundary {
+                    seen += 1;
+                    i += 1;
+                } else {
+                    break;
+                }
+            }
+            *slot = seen;
+        }
+        table
+    }
+
+    pub fn query<'a>(&'a self, ip: &str) -> Option<&'a ASNEntry> {
+        if let Ok(parsed_ip) = ip.parse() {
+            let asn = match parsed_ip {
+                IpAddr::V4(ipv4) => self.find_ipv4(u32::from(ipv4)),
+                IpAddr::V6(ipv6) => self.find_ipv6(u128::from(ipv6)),
+            };
+            asn.and_then(|asn| self.asn_map.get(&asn))
+        } else {
+            None
+        }
+    }
+
+    fn find_ipv4(&self, needle: u32) -> Option<u32> {
+        let record = |i: usize| {
+            let base = self.ipv4_offset + i * IPV4_RECORD_SIZE;
+            let bytes = &self.mmap[base..base + IPV4_RECORD_SIZE];
+            let start = BigEndian::read_u32(&bytes[0..4]);
+            let end = BigEndian::read_u32(&bytes[4..8]);
+            let asn = BigEndian::read_u32(&bytes[8..12]);
+            (start, end, asn)
+        };
+        let bucket = (needle >> 16) as usize;
+        let lo = self.ipv4_index.get(&self.mmap, bucket) as usize;
+        let hi = if bucket + 1 < INDEX_BUCKETS {
+            self.ipv4_index.get(&self.mmap, bucket + 1) as usize
+        } else {
+            self.ipv4_count
+        };
+        let i = Self::bounded_last_le(lo, hi, needle, |i| record(i).0)?;
+        let (_, end, asn) = record(i);
+        (end >= needle).then_some(asn)
+    }
+
+    fn find_ipv6(&self, needle: u128) -> Option<u32> {
+        let record = |i: usize| {
+            let base = self.ipv6_offset + i * IPV6_RECORD_SIZE;
+            let bytes = &self.mmap[base..base + IPV6_RECORD_SIZE];
+            let start = BigEndian::read_u128(&bytes[0..16]);
+            let end = BigEndian::read_u128(&bytes[16..32]);
+            let asn = BigEndian::read_u32(&bytes[32..36]);
+            (start, end, asn)
+        };
+        let bucket = (needle >> 112) as usize;
+        let lo = self.ipv6_index.get(&self.mmap, bucket) as usize;
+        let hi = if bucket + 1 < INDEX_BUCKETS {
+            self.ipv6_index.get(&self.mmap, bucket + 1) as usize
+        } else {
+            self.ipv6_count
+        };
+        let i = Self::bounded_last_le(lo, hi, needle, |i| record(i).0)?;
+        let (_, end, asn) = record(i);
+        (end >= needle).then_some(asn)
+    }
+
+    /// Binary-searches `[lo, hi)` for the last index whose `start(index) <= needle`.
+    /// Falls back to `lo - 1` when the bucket's own window doesn't contain a
+    /// match, e.g. a point covered by a large range that began in an earlier bucket.
+    fn bounded_last_le<T: Ord>(
+        lo: usize,
+        hi: usize,
+        needle: T,
+        start: impl Fn(usize) -> T,
+    ) -> Option<usize> {
+        let (mut l, mut h) = (lo, hi);
+        while l < h {
+            let mid = l + (h - l) / 2;
+            if start(mid) <= needle {
+                l = mid + 1;
+            } else {
+                h = mid;
+            }
+        }
+        if l > lo {
+            Some(l - 1)
+        } else if lo > 0 {
+            Some(lo - 1)
+        } else {
+            None
+        }
+    }
+}
+
+impl IPDatabase {
+    /// Opens `path` as a memory-mapped, zero-copy querier instead of loading
+    /// it into owned `BTreeSet`s like [`IPDatabase::load_from_file`]. See
+    /// [`MmapIPDatabase`] for what stays mapped versus what's eagerly read.
+    pub fn open_mmap(path: &str) -> io::Result<MmapIPDatabase> {
+        MmapIPDatabase::open(path)
+    }
+}
+
+/// Reads a big-endian integer directly out of a byte slice; used to rebuild
+/// the prefix index for files saved before it existed, without involving the
+/// generic `IPRangeSet` storage.
+trait TryFromBigEndian {
+    fn read_be(bytes: &[u8]) -> Self;
+}
+
+impl TryFromBigEndian for u32 {
+    #[inline]
+    fn read_be(bytes: &[u8]) -> Self {
+        BigEndian::read_u32(bytes)
+    }
+}
+
+impl TryFromBigEndian for u128 {
+    #[inline]
+    fn read_be(bytes: &[u8]) -> Self {
+        BigEndian::read_u128(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounded_last_le_finds_the_last_match_in_window() {
+        let starts = [10u32, 20, 30, 40];
+        let at = |i: usize| starts[i];
+        assert_eq!(MmapIPDatabase::bounded_last_le(0, 4, 25u32, at), Some(1));
+        assert_eq!(MmapIPDatabase::bounded_last_le(0, 4, 40u32, at), Some(3));
+        assert_eq!(MmapIPDatabase::bounded_last_le(0, 4, 5u32, at), None);
+    }
+
+    #[test]
+    fn bounded_last_le_falls_back_to_lo_minus_one_on_an_empty_window() {
+        // An empty bucket window (lo == hi) means no range starts in this
+        // bucket; the match, if any, is the last record before it.
+        let starts = [10u32, 20, 30, 40];
+        let at = |i: usize| starts[i];
+        assert_eq!(MmapIPDatabase::bounded_last_le(2, 2, 35u32, at), Some(1));
+        // A window starting at 0 with nothing before it has no fallback.
+        assert_eq!(MmapIPDatabase::bounded_last_le(0, 0, 5u32, at), None);
+    }
+
+    /// Builds a `.bin` file with ranges deliberately placed to leave gaps
+    /// between 16-bit-prefix buckets, then checks that the mmap querier
+    /// agrees with the baseline `BTreeSet`-backed `IPDatabase::query` for
+    /// needles inside, outside, and in the empty buckets between ranges.
+    #[test]
+    fn mmap_querier_matches_baseline_for_sparse_ranges() {
+        // Bucket 0x0001 entirely covered, bucket 0x0003 untouched, a single
+        // address at the start of bucket 0x0005, nothing at all afterwards.
+        let tsv = "0.1.0.0\t0.1.255.255\t1\tUS\tfirst\n0.5.0.0\t0.5.0.0\t2\tDE\tsecond\n";
+        let mut db = IPDatabase::new();
+        db.load_from_tsv(&mut std::io::Cursor::new(tsv.as_bytes()))
+            .unwrap();
+
+        let path = std::env::temp_dir().join(format!(
+            "asndb-mmap-test-{}-{:?}.bin",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        db.save_to_file(path.to_str().unwrap()).unwrap();
+        let mmap_db = IPDatabase::open_mmap(path.to_str().unwrap()).unwrap();
+
+        let needles: [u32; 6] = [
+            0x0000_ffff, // just before the first range
+            0x0001_0000, // start of the first range
+            0x0001_ffff, // end of the first range
+            0x0002_0000, // empty bucket right after
+            0x0004_ffff, // empty bucket right before the lone address
+            0x0005_0000, // the lone address itself
+        ];
+        for needle in needles {
+            let ip = std::net::Ipv4Addr::from(needle).to_string();
+            let expected = db.query(&ip).map(|e| e.asn);
+            let actual = mmap_db.query(&ip).map(|e| e.asn);
+            assert_eq!(actual, expected, "mismatch for {ip}");
+        }
+
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -4,16 +4,17 @@ use std::collections::{BTreeSet, HashMap};
 use std::fs::File;
 use std::hash::BuildHasherDefault;
 use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::ops::RangeBounds;
+use std::sync::OnceLock;
 use twox_hash::XxHash64;
 
 #[derive(Debug, Clone)]
 /// ASN is IP routing data identified by its whatever number
 pub struct ASNEntry {
-    asn: u32,
-    country: [u8; 2],
-    description: String,
+    pub(crate) asn: u32,
+    pub(crate) country: [u8; 2],
+    pub(crate) description: String,
 }
 
 impl ASNEntry {
@@ -112,6 +113,41 @@ impl<T: Ord + Send + Sync + Default + Copy> IPRangeSet<T> {
             None => None,
         }
     }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Builds the on-disk prefix jump table for a range set: `table[b]` is the
+/// number of ranges whose `starts` is strictly below the bucket boundary
+/// `bucket_start(b)`, i.e. the lower-bound record position for bucket `b`.
+pub(crate) fn build_index<T: Ord + Send + Sync + Default + Copy>(
+    ranges: &IPRangeSet<T>,
+    bucket_start: impl Fn(usize) -> T,
+) -> Vec<u32> {
+    let mut table = vec![0u32; INDEX_BUCKETS];
+    let mut it = ranges.0.iter().peekable();
+    let mut count = 0u32;
+    for (b, slot) in table.iter_mut().enumerate() {
+        let boundary = bucket_start(b);
+        while let Some(entry) = it.peek() {
+            if entry.starts < boundary {
+                count += 1;
+                it.next();
+            } else {
+                break;
+            }
+        }
+        *slot = count;
+    }
+    table
 }
 
 /// IPv4+IPv6 Query system
@@ -119,11 +155,76 @@ pub struct IPDatabase {
     ipv4: IPRangeSet<u32>,
     ipv6: IPRangeSet<u128>,
     asn_map: HashMap<u32, ASNEntry, BuildHasherDefault<XxHash64>>,
+    /// ASN/country -> ranges, built lazily on first reverse lookup.
+    reverse: OnceLock<ReverseIndex>,
+}
+
+/// Inverse of the forward IP->ASN tables: ASN -> its ranges, and
+/// country -> its ASNs. Stores plain `(start, end)` pairs rather than
+/// cloned `IPRangeEntry` values, so building it never touches the
+/// string pool or `ASNEntry::description`; the `(start, end)` integers
+/// themselves are copied out of the `IPRangeSet`; because the set is
+/// keyed solely by `starts`, grouping its entries by `asn` while
+/// borrowing from it would tie this self-built index's lifetime to the
+/// borrow for as long as it lives, which `OnceLock`-based lazy init
+/// can't express safely.
+struct ReverseIndex {
+    ipv4_by_asn: HashMap<u32, Vec<(u32, u32)>, BuildHasherDefault<XxHash64>>,
+    ipv6_by_asn: HashMap<u32, Vec<(u128, u128)>, BuildHasherDefault<XxHash64>>,
+    asns_by_country: HashMap<[u8; 2], Vec<u32>, BuildHasherDefault<XxHash64>>,
+}
+
+impl ReverseIndex {
+    fn build(
+        ipv4: &IPRangeSet<u32>,
+        ipv6: &IPRangeSet<u128>,
+        asn_map: &HashMap<u32, ASNEntry, BuildHasherDefault<XxHash64>>,
+    ) -> Self {
+        let mut ipv4_by_asn = HashMap::<_, _, BuildHasherDefault<XxHash64>>::default();
+        for entry in ipv4.0.iter() {
+            ipv4_by_asn
+                .entry(entry.asn)
+                .or_insert_with(Vec::new)
+                .push((entry.starts, entry.ends));
+        }
+
+        let mut ipv6_by_asn = HashMap::<_, _, BuildHasherDefault<XxHash64>>::default();
+        for entry in ipv6.0.iter() {
+            ipv6_by_asn
+                .entry(entry.asn)
+                .or_insert_with(Vec::new)
+                .push((entry.starts, entry.ends));
+        }
+
+        let mut asns_by_country = HashMap::<_, _, BuildHasherDefault<XxHash64>>::default();
+        for entry in asn_map.values() {
+            asns_by_country
+                .entry(entry.country)
+                .or_insert_with(Vec::new)
+                .push(entry.asn);
+        }
+
+        Self {
+            ipv4_by_asn,
+            ipv6_by_asn,
+            asns_by_country,
+        }
+    }
 }
 
-const HEADER_SIZE: usize = 1024;
-const SIGNATURE: &[u8; 16] = b"_IPRANGECACHE_DB";
-const VERSION: u16 = 0x2;
+pub(crate) const HEADER_SIZE: usize = 1024;
+pub(crate) const SIGNATURE: &[u8; 16] = b"_IPRANGECACHE_DB";
+/// Version before the prefix index tables were introduced; `load` still
+/// understands it so old `.bin` files keep working.
+pub(crate) const OLD_VERSION: u16 = 0x2;
+pub(crate) const VERSION: u16 = 0x3;
+pub(crate) const ASN_RECORD_SIZE: usize = 14;
+pub(crate) const IPV4_RECORD_SIZE: usize = 12;
+pub(crate) const IPV6_RECORD_SIZE: usize = 36;
+/// Number of buckets in the prefix jump table: one per possible value of the
+/// top 16 bits of an address.
+pub(crate) const INDEX_BUCKETS: usize = 65536;
+pub(crate) const INDEX_SECTION_SIZE: usize = INDEX_BUCKETS * 4;
 
 impl IPDatabase {
     pub fn new() -> Self {
@@ -131,11 +232,59 @@ impl IPDatabase {
             ipv4: IPRangeSet(BTreeSet::new()),
             ipv6: IPRangeSet(BTreeSet::new()),
             asn_map: HashMap::<_, _, BuildHasherDefault<XxHash64>>::default(),
+            reverse: OnceLock::new(),
         }
     }
 
+    fn reverse_index(&self) -> &ReverseIndex {
+        self.reverse
+            .get_or_init(|| ReverseIndex::build(&self.ipv4, &self.ipv6, &self.asn_map))
+    }
+
+    /// All ranges announced by `asn`, as `(range_start, range_end)` pairs.
+    pub fn ranges_for_asn<'a>(&'a self, asn: u32) -> impl Iterator<Item = (IpAddr, IpAddr)> + 'a {
+        let index = self.reverse_index();
+        let v4 = index
+            .ipv4_by_asn
+            .get(&asn)
+            .into_iter()
+            .flatten()
+            .map(|&(start, end)| {
+                (
+                    IpAddr::V4(Ipv4Addr::from(start)),
+                    IpAddr::V4(Ipv4Addr::from(end)),
+                )
+            });
+        let v6 = index
+            .ipv6_by_asn
+            .get(&asn)
+            .into_iter()
+            .flatten()
+            .map(|&(start, end)| {
+                (
+                    IpAddr::V6(Ipv6Addr::from(start)),
+                    IpAddr::V6(Ipv6Addr::from(end)),
+                )
+            });
+        v4.chain(v6)
+    }
+
+    /// All ASNs registered under the given 2-letter country code.
+    pub fn asns_for_country<'a>(&'a self, country: &str) -> impl Iterator<Item = u32> + 'a {
+        let bytes = country.as_bytes();
+        let code = if bytes.len() == 2 {
+            Some([bytes[0], bytes[1]])
+        } else {
+            None
+        };
+        code.and_then(|code| self.reverse_index().asns_by_country.get(&code))
+            .into_iter()
+            .flatten()
+            .copied()
+    }
+
     pub fn load_from_tsv_file(&mut self, path: &str) -> io::Result<()> {
-        let file = std::fs::File::create(path)?;
+        let file = std::fs::File::open(path)?;
         let mut file = std::io::BufReader::new(file);
         Self::load_from_tsv(self, &mut file)
     }
@@ -198,6 +347,11 @@ impl IPDatabase {
         Ok(())
     }
 
+    /// Total number of IPv4 + IPv6 ranges currently loaded.
+    pub fn entry_count(&self) -> usize {
+        self.ipv4.len() + self.ipv6.len()
+    }
+
     pub fn query<'a>(&'a self, ip: &str) -> Option<&'a ASNEntry> {
         if let Ok(parsed_ip) = ip.parse() {
             match parsed_ip {
@@ -240,6 +394,15 @@ impl IPDatabase {
         // let mut hasher = XxHash32::with_seed(727);
         let mut strpool = StringPool::new();
 
+        let ipv4_index = build_index(&self.ipv4, |b| (b as u32) << 16);
+        for bucket in &ipv4_index {
+            file.write_u32::<BigEndian>(*bucket)?;
+        }
+        let ipv6_index = build_index(&self.ipv6, |b| (b as u128) << 112);
+        for bucket in &ipv6_index {
+            file.write_u32::<BigEndian>(*bucket)?;
+        }
+
         for (asn, entry) in &self.asn_map {
             // let reg_asn = &asn.to_le_bytes();
             let reg_rgn = &entry.country;
@@ -303,12 +466,20 @@ impl IPDatabase {
             ));
         }
         let version = BigEndian::read_u16(&header[16..18]);
-        if version != VERSION {
+        if version != VERSION && version != OLD_VERSION {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidData,
                 "Invalid version",
             ));
         }
+        // Versions from before the prefix index tables were introduced don't
+        // carry them; the regular BTreeSet-backed querier doesn't need the
+        // tables, so just skip over them when present.
+        let records_offset = if version == VERSION {
+            HEADER_SIZE + 2 * INDEX_SECTION_SIZE
+        } else {
+            HEADER_SIZE
+        };
         let asn_count = BigEndian::read_u32(&header[30..34]);
         let ipv4_count = BigEndian::read_u32(&header[34..38]);
         let ipv6_count = BigEndian::read_u32(&header[38..42]);
@@ -322,7 +493,7 @@ impl IPDatabase {
         let strpool = StringPool::load(strpl_buf);
 
         let mut db = IPDatabase::new();
-        file.seek(SeekFrom::Start(HEADER_SIZE as u64))?;
+        file.seek(SeekFrom::Start(records_offset as u64))?;
 
         for _ in 0..asn_count {
             // let _hash = file.read_u32::<BigEndian>()?;
@@ -368,3 +539,33 @@ impl IPDatabase {
         Ok(Self::load(&mut file)?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_index_counts_ranges_strictly_below_each_bucket_boundary() {
+        let mut ranges = IPRangeSet(BTreeSet::new());
+        // One range entirely inside bucket 0, one starting in bucket 2 and
+        // spanning into bucket 3, and a lone range in bucket 5 — leaving
+        // bucket 4 with no range starting in it.
+        ranges.insert(10, 20, 1);
+        ranges.insert(bucket_of_start(2), bucket_of_start(3) + 5, 2);
+        ranges.insert(bucket_of_start(5), bucket_of_start(5) + 1, 3);
+
+        let table = build_index(&ranges, |b| (b as u32) << 16);
+
+        assert_eq!(table[0], 0); // nothing starts below bucket 0
+        assert_eq!(table[1], 1); // the bucket-0 range is now behind us
+        assert_eq!(table[2], 1); // the bucket-2 range hasn't started yet
+        assert_eq!(table[3], 2); // it started in bucket 2
+        assert_eq!(table[4], 2); // bucket 4 has no range of its own
+        assert_eq!(table[5], 2);
+        assert_eq!(table[6], 3); // the bucket-5 range is now behind us
+    }
+
+    fn bucket_of_start(b: u32) -> u32 {
+        b << 16
+    }
+}